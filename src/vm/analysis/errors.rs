@@ -0,0 +1,71 @@
+use std::fmt;
+
+use vm::representations::SymbolicExpression;
+
+pub type CheckResult<T> = Result<T, CheckError>;
+
+/// The set of ways a contract can fail static analysis. Kept narrow to the
+/// variants the analysis passes in this module actually raise; the broader
+/// type-checker-facing diagnostics live alongside the type checker itself.
+#[derive(Debug, Clone)]
+pub enum CheckErrors {
+    DefineFunctionBadSignature,
+    BadFunctionName,
+    BadLetSyntax,
+    BadSyntaxBinding,
+    TupleExpectsPairs,
+    ContractCallExpectName,
+    NonFunctionApplication,
+    UnknownFunction(String),
+    WriteAttemptedInReadOnly,
+    IncorrectArgumentCount(usize, usize),
+    RequiresAtLeastArguments(usize, usize),
+}
+
+impl fmt::Display for CheckErrors {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct CheckError {
+    pub err: CheckErrors,
+    pub expression: Option<SymbolicExpression>,
+}
+
+impl CheckError {
+    pub fn new(err: CheckErrors) -> CheckError {
+        CheckError { err, expression: None }
+    }
+
+    pub fn has_expression(&self) -> bool {
+        self.expression.is_some()
+    }
+
+    pub fn set_expression(&mut self, expr: &SymbolicExpression) {
+        self.expression = Some(expr.clone());
+    }
+}
+
+impl From<CheckErrors> for CheckError {
+    fn from(err: CheckErrors) -> Self {
+        CheckError::new(err)
+    }
+}
+
+pub fn check_argument_count<T>(expected: usize, args: &[T]) -> CheckResult<()> {
+    if args.len() != expected {
+        Err(CheckErrors::IncorrectArgumentCount(expected, args.len()).into())
+    } else {
+        Ok(())
+    }
+}
+
+pub fn check_arguments_at_least<T>(expected: usize, args: &[T]) -> CheckResult<()> {
+    if args.len() < expected {
+        Err(CheckErrors::RequiresAtLeastArguments(expected, args.len()).into())
+    } else {
+        Ok(())
+    }
+}
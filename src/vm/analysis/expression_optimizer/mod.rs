@@ -0,0 +1,285 @@
+use vm::representations::{SymbolicExpressionType, SymbolicExpression, ClarityName};
+use vm::representations::SymbolicExpressionType::{AtomValue, Atom, List, LiteralValue};
+use vm::types::Value;
+use vm::functions::NativeFunctions;
+use vm::functions::define::DefineFunctions;
+use vm::analysis::types::{ContractAnalysis, AnalysisPass};
+use vm::analysis::read_only_checker::ReadOnlyChecker;
+
+use std::convert::TryFrom;
+
+use super::AnalysisDatabase;
+pub use super::errors::{CheckResult, CheckError, CheckErrors};
+
+#[cfg(test)]
+mod tests;
+
+/// Rewrites a contract's expression tree using the read-only classification
+/// computed by `ReadOnlyChecker`: a read-only subexpression has no side
+/// effects, so it's safe to constant-fold (when every argument is already a
+/// literal) or to de-duplicate (when the exact same call recurs inside a
+/// single `let`/`begin` scope). Neither transform ever reorders a
+/// non-read-only expression, and de-duplication never links two occurrences
+/// that straddle a write, since the write may change what the second
+/// occurrence would have read.
+pub struct ExpressionOptimizer <'a, 'b> {
+    read_only_checker: ReadOnlyChecker<'a, 'b>,
+}
+
+impl <'a, 'b> AnalysisPass for ExpressionOptimizer <'a, 'b> {
+
+    fn run_pass(contract_analysis: &mut ContractAnalysis, analysis_db: &mut AnalysisDatabase) -> CheckResult<()> {
+        let mut read_only_checker = ReadOnlyChecker::new(analysis_db);
+        read_only_checker.run(contract_analysis)?;
+
+        let mut command = ExpressionOptimizer { read_only_checker };
+        command.run(contract_analysis)
+    }
+}
+
+impl <'a, 'b> ExpressionOptimizer <'a, 'b> {
+
+    pub fn run(&mut self, contract_analysis: &mut ContractAnalysis) -> CheckResult<()> {
+        for expr in contract_analysis.expressions_mut().iter_mut() {
+            self.optimize_defined_function_body(expr)?;
+        }
+        Ok(())
+    }
+
+    /// Only a `define-private`/`define-public`/`define-read-only` form's
+    /// *body* is a function-application tree this pass can safely walk --
+    /// the surrounding `(define-... (name args...) body)` and the other
+    /// top-level forms (`define-map`, `define-constant`, ...) aren't
+    /// themselves expressions `ReadOnlyChecker` (or `NativeFunctions`) knows
+    /// how to classify, so feeding them to `optimize_expr` directly would
+    /// misread the `define-*` keyword as an unresolved function call.
+    fn optimize_defined_function_body(&mut self, expr: &mut SymbolicExpression) -> CheckResult<()> {
+        use vm::functions::define::DefineFunctions::*;
+
+        let is_function_define = matches!(DefineFunctions::try_parse(expr),
+            Some((PrivateFunction, _)) | Some((PublicFunction, _)) | Some((ReadOnlyFunction, _)));
+
+        if !is_function_define {
+            return Ok(())
+        }
+
+        let children = expr.match_list_mut()
+            .ok_or(CheckErrors::DefineFunctionBadSignature)?;
+        let body = children.get_mut(2)
+            .ok_or(CheckErrors::DefineFunctionBadSignature)?;
+        self.optimize_expr(body)
+    }
+
+    fn is_read_only(&mut self, expr: &SymbolicExpression) -> CheckResult<bool> {
+        Ok(self.read_only_checker.get_write_footprint(expr)?.is_empty())
+    }
+
+    fn optimize_expr(&mut self, expr: &mut SymbolicExpression) -> CheckResult<()> {
+        if let List(ref mut children) = expr.expr {
+            for child in children.iter_mut() {
+                self.optimize_expr(child)?;
+            }
+
+            let native_op = children.get(0)
+                .and_then(|op| op.match_atom())
+                .and_then(|name| NativeFunctions::lookup_by_name(name));
+
+            match native_op {
+                Some(NativeFunctions::Let) if children.len() >= 3 => {
+                    self.dedup_let_body(children)?;
+                },
+                Some(NativeFunctions::Begin) if children.len() >= 2 => {
+                    self.dedup_begin_body(expr)?;
+                    // `expr` may no longer be the `begin` we started with (it
+                    //  can have been wrapped in a synthetic `let`), so bail
+                    //  out of the constant-fold check below for this call --
+                    //  it will simply remain un-folded, which is always safe.
+                    return Ok(())
+                },
+                _ => {}
+            }
+        }
+
+        self.try_constant_fold(expr)
+    }
+
+    /// De-duplicate repeated read-only calls directly inside a `let`'s body,
+    /// binding each repeated call once more in the same `let`'s binding list.
+    /// This is always safe: every body expression already executes after
+    /// every existing binding, so appending another binding to the end of
+    /// the same list can't put a use ahead of its definition.
+    fn dedup_let_body(&mut self, children: &mut Vec<SymbolicExpression>) -> CheckResult<()> {
+        let new_bindings = self.dedup_scope(&mut children[2..])?;
+        if new_bindings.is_empty() {
+            return Ok(())
+        }
+
+        let binding_list = children[1].match_list_mut()
+            .ok_or(CheckErrors::BadLetSyntax)?;
+        for (name, value) in new_bindings {
+            binding_list.push(SymbolicExpression::list(vec![SymbolicExpression::atom(name), value]));
+        }
+        Ok(())
+    }
+
+    /// De-duplicate repeated read-only calls inside a `begin`'s body. Since a
+    /// bare `begin` has nowhere to hang new bindings, wrap it in a synthetic
+    /// `let` that binds each repeated call once before running the (rewired)
+    /// `begin`.
+    fn dedup_begin_body(&mut self, expr: &mut SymbolicExpression) -> CheckResult<()> {
+        let new_bindings = if let List(ref mut children) = expr.expr {
+            self.dedup_scope(&mut children[1..])?
+        } else {
+            return Ok(())
+        };
+
+        if new_bindings.is_empty() {
+            return Ok(())
+        }
+
+        let bindings = new_bindings.into_iter()
+            .map(|(name, value)| SymbolicExpression::list(vec![SymbolicExpression::atom(name), value]))
+            .collect();
+
+        let original_begin = expr.clone();
+        *expr = SymbolicExpression::list(vec![
+            SymbolicExpression::atom(ClarityName::try_from("let".to_string())
+                .expect("'let' is a valid ClarityName")),
+            SymbolicExpression::list(bindings),
+            original_begin,
+        ]);
+        Ok(())
+    }
+
+    /// Find read-only calls that recur -- with no intervening non-read-only
+    /// expression -- among `scope`'s direct children, and replace every
+    /// occurrence of each with a reference to a fresh name. Returns the
+    /// `(name, expression)` bindings the caller must introduce for those
+    /// names to resolve.
+    fn dedup_scope(&mut self, scope: &mut [SymbolicExpression]) -> CheckResult<Vec<(ClarityName, SymbolicExpression)>> {
+        let mut groups: Vec<(SymbolicExpression, Vec<usize>)> = Vec::new();
+        let mut live: Vec<usize> = Vec::new();
+        // The caller always splices a cached binding into the scope's
+        //  *bindings* position, ahead of every expression in the body -- so
+        //  once a non-read-only expression has run anywhere earlier in the
+        //  scope, no later occurrence can safely start a new cached group:
+        //  doing so would hoist its evaluation ahead of that write too, not
+        //  just ahead of the occurrences it's deduplicating against.
+        let mut write_seen = false;
+
+        for (index, slot) in scope.iter().enumerate() {
+            if !self.is_read_only(slot)? {
+                // A write between two otherwise-identical reads means the
+                //  second read can't be assumed to return the first's value.
+                write_seen = true;
+                live.clear();
+                continue
+            }
+
+            if !matches!(slot.expr, List(_)) {
+                continue
+            }
+
+            if let Some(&group) = live.iter().find(|&&g| Self::same_expr(&groups[g].0, slot)) {
+                groups[group].1.push(index);
+            } else if !write_seen {
+                groups.push((slot.clone(), vec![index]));
+                live.push(groups.len() - 1);
+            }
+        }
+
+        let mut new_bindings = Vec::new();
+        for (group_index, (expr, occurrences)) in groups.into_iter().enumerate() {
+            if occurrences.len() < 2 {
+                continue
+            }
+
+            let fresh_name = ClarityName::try_from(format!("cse-{}", group_index))
+                .expect("generated CSE binding name is a valid ClarityName");
+            for occurrence in occurrences {
+                scope[occurrence] = SymbolicExpression::atom(fresh_name.clone());
+            }
+            new_bindings.push((fresh_name, expr));
+        }
+
+        Ok(new_bindings)
+    }
+
+    fn same_expr(a: &SymbolicExpression, b: &SymbolicExpression) -> bool {
+        match (&a.expr, &b.expr) {
+            (Atom(a_name), Atom(b_name)) => a_name == b_name,
+            (AtomValue(a_val), AtomValue(b_val)) => a_val == b_val,
+            (LiteralValue(a_val), LiteralValue(b_val)) => a_val == b_val,
+            (List(a_children), List(b_children)) => {
+                a_children.len() == b_children.len() &&
+                    a_children.iter().zip(b_children.iter()).all(|(x, y)| Self::same_expr(x, y))
+            },
+            _ => false,
+        }
+    }
+
+    /// Fold a read-only call whose arguments are all already literals into a
+    /// single `LiteralValue`. Only a small set of pure arithmetic/logic
+    /// operations is handled -- anything else is left as-is.
+    fn try_constant_fold(&mut self, expr: &mut SymbolicExpression) -> CheckResult<()> {
+        let folded = match expr.expr {
+            List(ref children) => {
+                if !self.is_read_only(expr)? {
+                    None
+                } else {
+                    match children.split_first() {
+                        Some((op_expr, args)) => {
+                            let op = op_expr.match_atom().and_then(|name| NativeFunctions::lookup_by_name(name));
+                            let literals: Option<Vec<Value>> = args.iter().map(Self::as_literal).collect();
+                            match (op, literals) {
+                                (Some(op), Some(values)) => Self::fold_native(&op, &values),
+                                _ => None,
+                            }
+                        },
+                        None => None,
+                    }
+                }
+            },
+            _ => None,
+        };
+
+        if let Some(value) = folded {
+            *expr = SymbolicExpression::literal_value(value);
+        }
+        Ok(())
+    }
+
+    fn as_literal(expr: &SymbolicExpression) -> Option<Value> {
+        match expr.expr {
+            LiteralValue(ref value) => Some(value.clone()),
+            AtomValue(ref value) => Some(value.clone()),
+            _ => None,
+        }
+    }
+
+    fn fold_native(op: &NativeFunctions, args: &[Value]) -> Option<Value> {
+        use vm::functions::NativeFunctions::*;
+        let (first, rest) = args.split_first()?;
+        match op {
+            Add => rest.iter().cloned()
+                .try_fold(first.clone(), |acc, next| Self::checked_arith(acc, next, i128::checked_add, u128::checked_add)),
+            Subtract => rest.iter().cloned()
+                .try_fold(first.clone(), |acc, next| Self::checked_arith(acc, next, i128::checked_sub, u128::checked_sub)),
+            Multiply => rest.iter().cloned()
+                .try_fold(first.clone(), |acc, next| Self::checked_arith(acc, next, i128::checked_mul, u128::checked_mul)),
+            Not => match (first, rest.len()) {
+                (Value::Bool(value), 0) => Some(Value::Bool(!value)),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    fn checked_arith(acc: Value, next: Value, int_op: fn(i128, i128) -> Option<i128>, uint_op: fn(u128, u128) -> Option<u128>) -> Option<Value> {
+        match (acc, next) {
+            (Value::Int(a), Value::Int(b)) => int_op(a, b).map(Value::Int),
+            (Value::UInt(a), Value::UInt(b)) => uint_op(a, b).map(Value::UInt),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,111 @@
+use super::*;
+use vm::analysis::AnalysisDatabase;
+
+fn atom(name: &str) -> SymbolicExpression {
+    SymbolicExpression::atom(ClarityName::try_from(name.to_string()).unwrap())
+}
+
+fn uint(value: u128) -> SymbolicExpression {
+    SymbolicExpression::literal_value(Value::UInt(value))
+}
+
+fn list(items: Vec<SymbolicExpression>) -> SymbolicExpression {
+    SymbolicExpression::list(items)
+}
+
+fn new_optimizer<'a, 'b>(db: &'a mut AnalysisDatabase<'b>) -> ExpressionOptimizer<'a, 'b> {
+    ExpressionOptimizer { read_only_checker: ReadOnlyChecker::new(db) }
+}
+
+fn is_let(expr: &SymbolicExpression) -> bool {
+    let let_name = ClarityName::try_from("let".to_string()).unwrap();
+    match expr.expr {
+        List(ref children) => children.get(0).and_then(|c| c.match_atom()).map(|n| n == &let_name).unwrap_or(false),
+        _ => false,
+    }
+}
+
+fn define_private(function_name: &str, body: SymbolicExpression) -> SymbolicExpression {
+    list(vec![atom("define-private"), list(vec![atom(function_name)]), body])
+}
+
+/// `run_pass` -- the only entry point a real analysis pipeline calls -- must
+/// be able to process an actual contract: a top-level `define-*` form isn't
+/// itself a function-application tree `ReadOnlyChecker` can classify, so it
+/// must never be handed to `optimize_expr` directly. Only a defined
+/// function's body should be walked and rewritten; other top-level forms
+/// (`define-map`, ...) must pass through untouched.
+#[test]
+fn run_pass_optimizes_function_bodies_without_erroring_on_top_level_forms() {
+    let mut contract_analysis = ContractAnalysis::new(vec![
+        define_private("add-one-two", list(vec![atom("+"), uint(1), uint(2)])),
+        list(vec![atom("define-map"), atom("my-map"), atom("uint"), atom("uint")]),
+    ]);
+    let mut db = AnalysisDatabase::new();
+
+    ExpressionOptimizer::run_pass(&mut contract_analysis, &mut db).unwrap();
+
+    let defined_function = contract_analysis.expressions_mut()[0].match_list().unwrap();
+    match defined_function[2].expr {
+        LiteralValue(Value::UInt(3)) => {},
+        ref other => panic!("expected the function body to be constant-folded to 3, got {:?}", other),
+    }
+
+    let map_define = contract_analysis.expressions_mut()[1].match_list().unwrap();
+    assert_eq!(map_define.len(), 4, "non-function top-level forms must pass through untouched");
+}
+
+/// A write between two otherwise-identical read-only calls must never be
+/// reordered behind a cached binding: the cache is always spliced into the
+/// scope's bindings position, ahead of the whole body, so caching an
+/// occurrence that follows a write would hoist its evaluation ahead of that
+/// write too.
+#[test]
+fn begin_body_does_not_hoist_cached_read_across_a_preceding_write() {
+    let mut db = AnalysisDatabase::new();
+    let mut optimizer = new_optimizer(&mut db);
+
+    let mut expr = list(vec![
+        atom("begin"),
+        list(vec![atom("set-var!"), atom("counter"),
+            list(vec![atom("+"), list(vec![atom("fetch-var"), atom("counter")]), uint(1)])]),
+        list(vec![atom("fetch-var"), atom("counter")]),
+        list(vec![atom("+"), uint(2), uint(3)]),
+        list(vec![atom("fetch-var"), atom("counter")]),
+    ]);
+    // The all-literal `(+ 2 3)` is still constant-folded in place; nothing
+    // else in the `begin` changes.
+    let mut expected = expr.clone();
+    if let List(ref mut children) = expected.expr {
+        children[3] = uint(5);
+    }
+
+    optimizer.optimize_expr(&mut expr).unwrap();
+
+    // No safe cache exists for `(fetch-var counter)` here -- both occurrences
+    // follow the `set-var!` -- so the `begin` must stay a `begin` rather
+    // than be wrapped in a synthetic `let` that would hoist the cached read
+    // ahead of the write.
+    assert!(ExpressionOptimizer::same_expr(&expr, &expected), "expected begin body to be left as-is apart from constant folding, got {:?}", expr);
+    assert!(!is_let(&expr));
+}
+
+/// Occurrences that recur with no write between them (or before them) are
+/// still safe to de-duplicate via a binding, since hoisting them changes
+/// nothing about when they run relative to the rest of the scope.
+#[test]
+fn begin_body_still_dedups_repeats_that_precede_any_write() {
+    let mut db = AnalysisDatabase::new();
+    let mut optimizer = new_optimizer(&mut db);
+
+    let mut expr = list(vec![
+        atom("begin"),
+        list(vec![atom("fetch-var"), atom("counter")]),
+        list(vec![atom("fetch-var"), atom("counter")]),
+        list(vec![atom("set-var!"), atom("counter"), uint(5)]),
+    ]);
+
+    optimizer.optimize_expr(&mut expr).unwrap();
+
+    assert!(is_let(&expr), "expected repeats preceding the write to be hoisted into a synthetic let, got {:?}", expr);
+}
@@ -0,0 +1,40 @@
+pub mod errors;
+pub mod types;
+pub mod reduced_ir;
+pub mod read_only_checker;
+pub mod expression_optimizer;
+
+use std::collections::HashMap;
+use std::marker::PhantomData;
+
+use vm::representations::ClarityName;
+use vm::types::QualifiedContractIdentifier;
+use vm::analysis::errors::CheckResult;
+use vm::analysis::read_only_checker::WriteFootprint;
+
+/// A node's view onto the analysis results of every contract it has already
+/// analyzed and persisted, keyed by contract identifier and then by function
+/// name. `'a` is carried only as a `PhantomData` marker; the struct holds no
+/// borrowed handle.
+pub struct AnalysisDatabase<'a> {
+    write_footprints: HashMap<(QualifiedContractIdentifier, ClarityName), WriteFootprint>,
+    _lifetime: PhantomData<&'a ()>,
+}
+
+impl <'a> AnalysisDatabase<'a> {
+    pub fn new() -> AnalysisDatabase<'a> {
+        AnalysisDatabase {
+            write_footprints: HashMap::new(),
+            _lifetime: PhantomData,
+        }
+    }
+
+    /// The write footprint of an already-analyzed function, for resolving a
+    /// `contract-call?` into another contract. `None` if this contract
+    /// hasn't been analyzed (or this function doesn't exist on it), in which
+    /// case the caller conservatively assumes unknown effects.
+    pub fn get_function_write_footprint(&self, contract_identifier: &QualifiedContractIdentifier, function_name: &ClarityName) -> CheckResult<Option<WriteFootprint>> {
+        let key = (contract_identifier.clone(), function_name.clone());
+        Ok(self.write_footprints.get(&key).cloned())
+    }
+}
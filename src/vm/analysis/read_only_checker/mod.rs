@@ -1,14 +1,14 @@
 use vm::representations::{SymbolicExpressionType, SymbolicExpression, ClarityName};
 use vm::representations::SymbolicExpressionType::{AtomValue, Atom, List, LiteralValue};
-use vm::types::{TypeSignature, TupleTypeSignature, Value, PrincipalData, parse_name_type_pairs};
+use vm::types::{Value, PrincipalData};
 use vm::functions::NativeFunctions;
-use vm::functions::define::DefineFunctions;
 use vm::functions::tuples;
 use vm::functions::tuples::TupleDefinitionType::{Implicit, Explicit};
 use vm::analysis::types::{ContractAnalysis, AnalysisPass};
+use vm::analysis::reduced_ir::{lower_defined_functions, DefinedFunctionKind};
 
 use vm::variables::NativeVariables;
-use std::collections::HashMap;
+use std::collections::{HashMap, BTreeSet};
 
 use super::AnalysisDatabase;
 pub use super::errors::{CheckResult, CheckError, CheckErrors, check_argument_count, check_arguments_at_least};
@@ -16,9 +16,68 @@ pub use super::errors::{CheckResult, CheckError, CheckErrors, check_argument_cou
 #[cfg(test)]
 mod tests;
 
+/// The set of persisted state a function (transitively) writes to: the
+/// `define-map`s it mutates, the `define-data-var`s it sets, and the
+/// fungible/non-fungible tokens it mints or transfers. A function is
+/// read-only exactly when its footprint is empty.
+///
+/// `requires_onchain_context` is tracked alongside the footprint because it's
+/// propagated through exactly the same constructs (`Let`, `Map`, `Fold`,
+/// `Filter`, `ContractCall`, ...): it's set once a function (transitively)
+/// touches `get-block-info` or `as-contract`, which read the surrounding
+/// transaction/block context and so can't be replayed meaningfully against a
+/// snapshot outside of a real transaction, even though they perform no
+/// writes themselves.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct WriteFootprint {
+    pub maps_written: BTreeSet<ClarityName>,
+    pub vars_written: BTreeSet<ClarityName>,
+    pub assets_touched: BTreeSet<ClarityName>,
+    pub requires_onchain_context: bool,
+    /// Set for a call into a function this analysis has no footprint for --
+    /// e.g. a public function in a contract that hasn't been analyzed, or
+    /// one analyzed before footprint tracking existed. Kept separate from
+    /// `vars_written` (rather than stuffing the callee's name in there) so
+    /// that disjointness checks built on the footprint's named sets stay
+    /// sound: two functions that each call a different unknown external
+    /// function must not spuriously appear to collide on the same var.
+    pub has_unknown_effects: bool,
+}
+
+impl WriteFootprint {
+    pub fn new() -> WriteFootprint {
+        WriteFootprint {
+            maps_written: BTreeSet::new(),
+            vars_written: BTreeSet::new(),
+            assets_touched: BTreeSet::new(),
+            requires_onchain_context: false,
+            has_unknown_effects: false,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.maps_written.is_empty() && self.vars_written.is_empty() && self.assets_touched.is_empty()
+            && !self.has_unknown_effects
+    }
+
+    /// Safe to evaluate off-chain against a snapshotted chainstate: no
+    /// writes, and no dependency on live transaction/block context.
+    pub fn is_off_chain_evaluable(&self) -> bool {
+        self.is_empty() && !self.requires_onchain_context
+    }
+
+    pub fn union(&mut self, other: &WriteFootprint) {
+        self.maps_written.extend(other.maps_written.iter().cloned());
+        self.vars_written.extend(other.vars_written.iter().cloned());
+        self.assets_touched.extend(other.assets_touched.iter().cloned());
+        self.requires_onchain_context = self.requires_onchain_context || other.requires_onchain_context;
+        self.has_unknown_effects = self.has_unknown_effects || other.has_unknown_effects;
+    }
+}
+
 pub struct ReadOnlyChecker <'a, 'b> {
     db: &'a mut AnalysisDatabase<'b>,
-    defined_functions: HashMap<ClarityName, bool>
+    defined_functions: HashMap<ClarityName, WriteFootprint>
 }
 
 impl <'a, 'b> AnalysisPass for ReadOnlyChecker <'a, 'b> {
@@ -31,87 +90,92 @@ impl <'a, 'b> AnalysisPass for ReadOnlyChecker <'a, 'b> {
 }
 
 impl <'a, 'b> ReadOnlyChecker <'a, 'b> {
-    
-    fn new(db: &'a mut AnalysisDatabase<'b>) -> ReadOnlyChecker<'a, 'b> {
-        Self { 
-            db, 
-            defined_functions: HashMap::new() 
+
+    pub(crate) fn new(db: &'a mut AnalysisDatabase<'b>) -> ReadOnlyChecker<'a, 'b> {
+        Self {
+            db,
+            defined_functions: HashMap::new()
         }
     }
 
     pub fn run(& mut self, contract_analysis: &mut ContractAnalysis) -> CheckResult<()> {
+        // Phase 1: lower every user-defined function's signature and body up
+        //  front, before computing any footprint. This is what lets a
+        //  function call another function defined later in the contract, or
+        //  call itself (directly or through a cycle of mutually recursive
+        //  private functions). The reduced IR is also handed to
+        //  `contract_analysis` below so later passes (e.g. the type checker)
+        //  don't need to re-parse the same `define-*` forms.
+        let reduced = lower_defined_functions(contract_analysis)?;
+
+        // Every defined function starts out at the bottom of the footprint
+        //  lattice (read-only), so that a forward or recursive reference to it
+        //  resolves to "no writes yet known" rather than failing outright.
+        for f_name in reduced.keys() {
+            self.defined_functions.insert(f_name.clone(), WriteFootprint::new());
+        }
 
-        for exp in contract_analysis.expressions_iter() {
-            let mut result = self.check_reads_only_valid(&exp);
-            if let Err(ref mut error) = result {
-                if !error.has_expression() {
-                    error.set_expression(&exp);
+        // Phase 2: compute each function's write footprint as a least fixpoint
+        //  over the call graph. Footprints only ever grow (union is monotone),
+        //  so repeatedly recomputing every function's footprint from the
+        //  current approximation of its callees is guaranteed to converge --
+        //  this is what makes mutually recursive functions resolve correctly,
+        //  without needing to compute strongly-connected components explicitly.
+        loop {
+            let mut changed = false;
+            for (f_name, function) in reduced.iter() {
+                let mut result = self.get_write_footprint(&function.body);
+                if let Err(ref mut error) = result {
+                    if !error.has_expression() {
+                        error.set_expression(&function.source_expression);
+                    }
+                }
+                let footprint = result?;
+                if self.defined_functions.get(f_name) != Some(&footprint) {
+                    self.defined_functions.insert(f_name.clone(), footprint);
+                    changed = true;
                 }
             }
-            result?
+            if !changed {
+                break
+            }
         }
 
-        Ok(())
-    }
-
-    fn check_define_function(&mut self, args: &[SymbolicExpression]) -> CheckResult<(ClarityName, bool)> {
-        check_argument_count(2, args)?;
-
-        let signature = args[0].match_list()
-            .ok_or(CheckErrors::DefineFunctionBadSignature)?;
-        let body = &args[1];
-
-        let function_name = signature.get(0)
-            .ok_or(CheckErrors::DefineFunctionBadSignature)?
-            .match_atom().ok_or(CheckErrors::BadFunctionName)?;
-
-        let is_read_only = self.is_read_only(body)?;
-
-        Ok((function_name.clone(), is_read_only))
-    }
-
-    fn check_reads_only_valid(&mut self, expr: &SymbolicExpression) -> CheckResult<()> {
-        use vm::functions::define::DefineFunctions::*;
-        if let Some((define_type, args)) = DefineFunctions::try_parse(expr) {
-            match define_type {
-                Constant | Map | PersistedVariable | FungibleToken | NonFungibleToken => {
-                    // None of these define types ever need to be checked for their
-                    //  read-onliness, since they're never invoked outside of contract initialization.
-                    Ok(())
-                },
-                PrivateFunction => {
-                    let (f_name, is_read_only) = self.check_define_function(args)?;
-                    self.defined_functions.insert(f_name, is_read_only);
-                    Ok(())
-                },
-                PublicFunction => {
-                    let (f_name, is_read_only) = self.check_define_function(args)?;
-                    self.defined_functions.insert(f_name, is_read_only);
-                    Ok(())
-                },
-                ReadOnlyFunction => {
-                    let (f_name, is_read_only) = self.check_define_function(args)?;
-                    if !is_read_only {
-                        Err(CheckErrors::WriteAttemptedInReadOnly.into())
-                    } else {
-                        self.defined_functions.insert(f_name, is_read_only);
-                        Ok(())
-                    }
-                },
+        // Phase 3: footprints have stabilized, so it's now safe to enforce that
+        //  every `define-read-only` function is actually free of writes.
+        for (f_name, function) in reduced.iter() {
+            if function.kind == DefinedFunctionKind::ReadOnly {
+                let footprint = self.defined_functions.get(f_name)
+                    .expect("fixpoint should have assigned every defined function a footprint");
+                if !footprint.is_empty() {
+                    let mut error: CheckError = CheckErrors::WriteAttemptedInReadOnly.into();
+                    error.set_expression(&function.source_expression);
+                    return Err(error)
+                }
             }
-        } else {
-            Ok(())
         }
+
+        // Surface each function's footprint (and, with it, its off-chain
+        //  evaluability) on the contract analysis so a node's query
+        //  interface can decide whether a call can be served from a
+        //  read-only replica without replaying block context.
+        contract_analysis.read_only_function_footprints(self.defined_functions.clone());
+        contract_analysis.set_reduced_ir(reduced);
+
+        Ok(())
     }
 
-    fn are_all_read_only(&mut self, initial: bool, expressions: &[SymbolicExpression]) -> CheckResult<bool> {
+    fn combine_footprints(&mut self, initial: WriteFootprint, expressions: &[SymbolicExpression]) -> CheckResult<WriteFootprint> {
         expressions.iter()
             .fold(Ok(initial),
                   |acc, argument| {
-                      Ok(acc? && self.is_read_only(&argument)?) })
+                      let mut acc = acc?;
+                      acc.union(&self.get_write_footprint(&argument)?);
+                      Ok(acc) })
     }
 
-    fn is_implicit_tuple_definition_read_only(&mut self, tuples: &[SymbolicExpression]) -> CheckResult<bool> {
+    fn implicit_tuple_footprint(&mut self, tuples: &[SymbolicExpression]) -> CheckResult<WriteFootprint> {
+        let mut footprint = WriteFootprint::new();
         for tuple_expr in tuples.iter() {
             let pair = tuple_expr.match_list()
                 .ok_or(CheckErrors::TupleExpectsPairs)?;
@@ -119,14 +183,12 @@ impl <'a, 'b> ReadOnlyChecker <'a, 'b> {
                 return Err(CheckErrors::TupleExpectsPairs.into())
             }
 
-            if !self.is_read_only(&pair[1])? {
-                return Ok(false)
-            }
+            footprint.union(&self.get_write_footprint(&pair[1])?);
         }
-        Ok(true)
+        Ok(footprint)
     }
 
-    fn try_native_function_check(&mut self, function: &str, args: &[SymbolicExpression]) -> Option<CheckResult<bool>> {
+    fn try_native_function_check(&mut self, function: &str, args: &[SymbolicExpression]) -> Option<CheckResult<WriteFootprint>> {
         if let Some(ref function) = NativeFunctions::lookup_by_name(function) {
             Some(self.handle_native_function(function, args))
         } else {
@@ -134,7 +196,7 @@ impl <'a, 'b> ReadOnlyChecker <'a, 'b> {
         }
     }
 
-    fn handle_native_function(&mut self, function: &NativeFunctions, args: &[SymbolicExpression]) -> CheckResult<bool> {
+    fn handle_native_function(&mut self, function: &NativeFunctions, args: &[SymbolicExpression]) -> CheckResult<WriteFootprint> {
         use vm::functions::NativeFunctions::*;
 
         match function {
@@ -143,40 +205,67 @@ impl <'a, 'b> ReadOnlyChecker <'a, 'b> {
             Sha512 | Sha512Trunc256 |
             ConsSome | ConsOkay | ConsError | DefaultTo | Expects | ExpectsErr | IsOkay | IsNone |
             ToUInt | ToInt |
-            ListCons | GetBlockInfo | TupleGet | Print | AsContract | Begin | FetchVar | GetTokenBalance | GetAssetOwner => {
-                self.are_all_read_only(true, args)
+            ListCons | TupleGet | Print | Begin | FetchVar | GetTokenBalance | GetAssetOwner => {
+                self.combine_footprints(WriteFootprint::new(), args)
             },
-            FetchEntry => {                
+            GetBlockInfo | AsContract => {
+                // Neither writes state, but both depend on the context of a
+                //  real transaction/block, so they can't be served from a
+                //  read-only, off-chain snapshot.
+                let mut footprint = self.combine_footprints(WriteFootprint::new(), args)?;
+                footprint.requires_onchain_context = true;
+                Ok(footprint)
+            },
+            FetchEntry => {
                 let res = match tuples::get_definition_type_of_tuple_argument(&args[1]) {
                     Implicit(ref tuple_expr) => {
-                        self.is_implicit_tuple_definition_read_only(tuple_expr)
+                        self.implicit_tuple_footprint(tuple_expr)
                     },
                     Explicit => {
-                        self.are_all_read_only(true, args)
+                        self.combine_footprints(WriteFootprint::new(), args)
                     }
                 };
                 res
             },
-            FetchContractEntry => {                
+            FetchContractEntry => {
                 let res = match tuples::get_definition_type_of_tuple_argument(&args[2]) {
                     Implicit(ref tuple_expr) => {
-                        self.is_implicit_tuple_definition_read_only(tuple_expr)
+                        self.implicit_tuple_footprint(tuple_expr)
                     },
                     Explicit => {
-                        self.are_all_read_only(true, args)
+                        self.combine_footprints(WriteFootprint::new(), args)
                     }
                 };
                 res
             },
-            SetEntry | DeleteEntry | InsertEntry | SetVar | MintAsset | MintToken | TransferAsset | TransferToken => {
-                Ok(false)
+            SetEntry | DeleteEntry | InsertEntry => {
+                let map_name = args[0].match_atom()
+                    .ok_or(CheckErrors::BadSyntaxBinding)?;
+                let mut footprint = WriteFootprint::new();
+                footprint.maps_written.insert(map_name.clone());
+                self.combine_footprints(footprint, &args[1..])
+            },
+            SetVar => {
+                let var_name = args[0].match_atom()
+                    .ok_or(CheckErrors::BadSyntaxBinding)?;
+                let mut footprint = WriteFootprint::new();
+                footprint.vars_written.insert(var_name.clone());
+                self.combine_footprints(footprint, &args[1..])
+            },
+            MintAsset | MintToken | TransferAsset | TransferToken => {
+                let asset_name = args[0].match_atom()
+                    .ok_or(CheckErrors::BadSyntaxBinding)?;
+                let mut footprint = WriteFootprint::new();
+                footprint.assets_touched.insert(asset_name.clone());
+                self.combine_footprints(footprint, &args[1..])
             },
             Let => {
                 check_arguments_at_least(2, args)?;
-    
+
                 let binding_list = args[0].match_list()
                     .ok_or(CheckErrors::BadLetSyntax)?;
 
+                let mut footprint = WriteFootprint::new();
                 for pair in binding_list.iter() {
                     let pair_expression = pair.match_list()
                         .ok_or(CheckErrors::BadSyntaxBinding)?;
@@ -184,36 +273,35 @@ impl <'a, 'b> ReadOnlyChecker <'a, 'b> {
                         return Err(CheckErrors::BadSyntaxBinding.into())
                     }
 
-                    if !self.is_read_only(&pair_expression[1])? {
-                        return Ok(false)
-                    }
+                    footprint.union(&self.get_write_footprint(&pair_expression[1])?);
                 }
 
-                self.are_all_read_only(true, &args[1..args.len()])
+                self.combine_footprints(footprint, &args[1..args.len()])
             },
             Map | Filter => {
                 check_argument_count(2, args)?;
-    
+
                 // note -- we do _not_ check here to make sure we're not mapping on
                 //      a special function. that check is performed by the type checker.
                 //   we're pretty directly violating type checks in this recursive step:
                 //   we're asking the read only checker to check whether a function application
                 //     of the _mapping function_ onto the rest of the supplied arguments would be
                 //     read-only or not.
-                self.is_function_application_read_only(args)
+                self.function_application_footprint(args)
             },
             Fold => {
                 check_argument_count(3, args)?;
-    
+
                 // note -- we do _not_ check here to make sure we're not folding on
                 //      a special function. that check is performed by the type checker.
                 //   we're pretty directly violating type checks in this recursive step:
                 //   we're asking the read only checker to check whether a function application
                 //     of the _folding function_ onto the rest of the supplied arguments would be
                 //     read-only or not.
-                self.is_function_application_read_only(args)
+                self.function_application_footprint(args)
             },
             TupleCons => {
+                let mut footprint = WriteFootprint::new();
                 for pair in args.iter() {
                     let pair_expression = pair.match_list()
                         .ok_or(CheckErrors::TupleExpectsPairs)?;
@@ -221,11 +309,9 @@ impl <'a, 'b> ReadOnlyChecker <'a, 'b> {
                         return Err(CheckErrors::TupleExpectsPairs.into())
                     }
 
-                    if !self.is_read_only(&pair_expression[1])? {
-                        return Ok(false)
-                    }
+                    footprint.union(&self.get_write_footprint(&pair_expression[1])?);
                 }
-                Ok(true)
+                Ok(footprint)
             },
             ContractCall => {
                 check_arguments_at_least(2, args)?;
@@ -237,13 +323,23 @@ impl <'a, 'b> ReadOnlyChecker <'a, 'b> {
                 let function_name = args[1].match_atom()
                     .ok_or(CheckErrors::ContractCallExpectName)?;
 
-                let is_function_read_only = self.db.get_read_only_function_type(&contract_identifier, function_name)?.is_some();
-                self.are_all_read_only(is_function_read_only, &args[2..])
+                let callee_footprint = self.db.get_function_write_footprint(&contract_identifier, function_name)?
+                    .unwrap_or_else(|| {
+                        // The callee isn't a function this analysis has a footprint for
+                        //  (e.g., it's a public function defined in another contract), so
+                        //  conservatively assume it writes state we can't see and may
+                        //  depend on on-chain context.
+                        let mut unknown = WriteFootprint::new();
+                        unknown.has_unknown_effects = true;
+                        unknown.requires_onchain_context = true;
+                        unknown
+                    });
+                self.combine_footprints(callee_footprint, &args[2..])
             }
         }
     }
 
-    fn is_function_application_read_only(&mut self, expression: &[SymbolicExpression]) -> CheckResult<bool> {
+    fn function_application_footprint(&mut self, expression: &[SymbolicExpression]) -> CheckResult<WriteFootprint> {
         let (function_name, args) = expression.split_first()
             .ok_or(CheckErrors::NonFunctionApplication)?;
 
@@ -253,24 +349,30 @@ impl <'a, 'b> ReadOnlyChecker <'a, 'b> {
         if let Some(result) = self.try_native_function_check(function_name, args) {
             result
         } else {
-            let is_function_read_only = self.defined_functions.get(function_name)
+            let footprint = self.defined_functions.get(function_name)
                 .ok_or(CheckErrors::UnknownFunction(function_name.to_string()))?
                 .clone();
-            self.are_all_read_only(is_function_read_only, args)
+            self.combine_footprints(footprint, args)
         }
     }
 
 
-    fn is_read_only(&mut self, expr: &SymbolicExpression) -> CheckResult<bool> {
+    /// The write footprint of every defined function this checker has
+    /// classified, keyed by function name.
+    pub(crate) fn defined_functions(&self) -> &HashMap<ClarityName, WriteFootprint> {
+        &self.defined_functions
+    }
+
+    pub(crate) fn get_write_footprint(&mut self, expr: &SymbolicExpression) -> CheckResult<WriteFootprint> {
         match expr.expr {
             AtomValue(_) | LiteralValue(_) => {
-                Ok(true)
+                Ok(WriteFootprint::new())
             },
             Atom(_) => {
-                Ok(true)
+                Ok(WriteFootprint::new())
             },
             List(ref expression) => {
-                self.is_function_application_read_only(expression)
+                self.function_application_footprint(expression)
             }
         }
     }
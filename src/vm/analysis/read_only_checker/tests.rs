@@ -0,0 +1,110 @@
+use super::*;
+use std::convert::TryFrom;
+
+fn name(n: &str) -> ClarityName {
+    ClarityName::try_from(n.to_string()).unwrap()
+}
+
+fn atom(n: &str) -> SymbolicExpression {
+    SymbolicExpression::atom(name(n))
+}
+
+fn uint(value: u128) -> SymbolicExpression {
+    SymbolicExpression::literal_value(Value::UInt(value))
+}
+
+fn list(items: Vec<SymbolicExpression>) -> SymbolicExpression {
+    SymbolicExpression::list(items)
+}
+
+fn define_private(function_name: &str, body: SymbolicExpression) -> SymbolicExpression {
+    list(vec![atom("define-private"), list(vec![atom(function_name)]), body])
+}
+
+fn define_read_only(function_name: &str, body: SymbolicExpression) -> SymbolicExpression {
+    list(vec![atom("define-read-only"), list(vec![atom(function_name)]), body])
+}
+
+/// Two private functions that call each other (through a cycle that would
+/// never terminate if actually evaluated) must still converge to a stable,
+/// correct footprint: the fixpoint loop keeps recomputing each function's
+/// footprint from its callees' latest approximation until nothing changes,
+/// rather than relying on the functions happening to be defined in an order
+/// that resolves the recursion in a single pass.
+#[test]
+fn mutual_recursion_converges_to_the_real_footprint() {
+    let ping_body = list(vec![atom("begin"),
+        list(vec![atom("set-var!"), atom("counter"), uint(1)]),
+        list(vec![atom("pong")])]);
+    let pong_body = list(vec![atom("ping")]);
+
+    let mut contract_analysis = ContractAnalysis::new(vec![
+        define_private("ping", ping_body),
+        define_private("pong", pong_body),
+    ]);
+    let mut db = AnalysisDatabase::new();
+    let mut checker = ReadOnlyChecker::new(&mut db);
+
+    checker.run(&mut contract_analysis).unwrap();
+
+    for f_name in &["ping", "pong"] {
+        let footprint = contract_analysis.get_write_footprint(&name(f_name))
+            .unwrap_or_else(|| panic!("expected a footprint for {}", f_name));
+        assert!(footprint.vars_written.contains(&name("counter")),
+            "expected {}'s footprint to include the var counter sets through the cycle, got {:?}", f_name, footprint);
+    }
+}
+
+/// A forward reference -- a function calling another defined later in the
+/// same contract -- must resolve through the same fixpoint, not just
+/// functions defined earlier in the file.
+#[test]
+fn forward_reference_resolves_the_callees_footprint() {
+    let caller_body = list(vec![atom("callee")]);
+    let callee_body = list(vec![atom("set-var!"), atom("counter"), uint(1)]);
+
+    let mut contract_analysis = ContractAnalysis::new(vec![
+        define_private("caller", caller_body),
+        define_private("callee", callee_body),
+    ]);
+    let mut db = AnalysisDatabase::new();
+    let mut checker = ReadOnlyChecker::new(&mut db);
+
+    checker.run(&mut contract_analysis).unwrap();
+
+    let footprint = contract_analysis.get_write_footprint(&name("caller")).unwrap();
+    assert!(footprint.vars_written.contains(&name("counter")));
+}
+
+/// `get-block-info` performs no writes, so a read-only function built
+/// around it must still pass the read-only check, but it depends on live
+/// block context and so must not be classified as off-chain evaluable.
+#[test]
+fn block_info_is_read_only_but_not_off_chain_evaluable() {
+    let body = list(vec![atom("get-block-info"), atom("time"), uint(0)]);
+    let mut contract_analysis = ContractAnalysis::new(vec![define_read_only("at-height", body)]);
+    let mut db = AnalysisDatabase::new();
+    let mut checker = ReadOnlyChecker::new(&mut db);
+
+    checker.run(&mut contract_analysis).unwrap();
+
+    let footprint = contract_analysis.get_write_footprint(&name("at-height")).unwrap();
+    assert!(footprint.is_empty());
+    assert!(footprint.requires_onchain_context);
+    assert!(!footprint.is_off_chain_evaluable());
+}
+
+/// A function with no writes and no dependency on block/transaction context
+/// is exactly the case a read-only query endpoint can serve off-chain.
+#[test]
+fn pure_function_is_off_chain_evaluable() {
+    let body = list(vec![atom("+"), uint(1), uint(2)]);
+    let mut contract_analysis = ContractAnalysis::new(vec![define_read_only("add-one-two", body)]);
+    let mut db = AnalysisDatabase::new();
+    let mut checker = ReadOnlyChecker::new(&mut db);
+
+    checker.run(&mut contract_analysis).unwrap();
+
+    let footprint = contract_analysis.get_write_footprint(&name("add-one-two")).unwrap();
+    assert!(footprint.is_off_chain_evaluable());
+}
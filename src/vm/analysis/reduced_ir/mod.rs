@@ -0,0 +1,93 @@
+use vm::representations::{SymbolicExpression, ClarityName};
+use vm::types::{TypeSignature, parse_name_type_pairs};
+use vm::functions::define::DefineFunctions;
+use vm::analysis::types::ContractAnalysis;
+
+use std::collections::HashMap;
+
+use super::errors::{CheckResult, CheckErrors, check_argument_count};
+
+#[cfg(test)]
+mod tests;
+
+/// Which of the three function-defining forms a `ReducedFunction` came from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DefinedFunctionKind {
+    Private,
+    Public,
+    ReadOnly,
+}
+
+/// A defined function's signature and body, already picked apart from its
+/// raw `define-*` expression. Built once, up front, so that every pass that
+/// needs a function's parameters or body -- the read-only checker, the type
+/// checker -- can consume it directly instead of re-running
+/// `DefineFunctions::try_parse`/`match_list`/`parse_name_type_pairs` itself.
+#[derive(Clone, Debug)]
+pub struct ReducedFunction {
+    pub kind: DefinedFunctionKind,
+    pub arguments: Vec<(ClarityName, TypeSignature)>,
+    pub body: SymbolicExpression,
+    /// The full `(define-... (name args...) body)` expression this function
+    /// was lowered from, kept around so later passes can attribute errors to
+    /// the same expression they would have before lowering existed.
+    pub source_expression: SymbolicExpression,
+}
+
+/// Walk a contract once, lowering every `define-private`/`define-public`/
+/// `define-read-only` form into a `ReducedFunction`. Non-function defines
+/// (`define-constant`, `define-map`, ...) have no function signature to
+/// recover and are skipped.
+pub fn lower_defined_functions(contract_analysis: &ContractAnalysis) -> CheckResult<HashMap<ClarityName, ReducedFunction>> {
+    use vm::functions::define::DefineFunctions::*;
+
+    let mut reduced = HashMap::new();
+    for expr in contract_analysis.expressions_iter() {
+        let (define_type, args) = match DefineFunctions::try_parse(&expr) {
+            Some(parsed) => parsed,
+            None => continue,
+        };
+
+        let kind = match define_type {
+            PrivateFunction => DefinedFunctionKind::Private,
+            PublicFunction => DefinedFunctionKind::Public,
+            ReadOnlyFunction => DefinedFunctionKind::ReadOnly,
+            Constant | Map | PersistedVariable | FungibleToken | NonFungibleToken => continue,
+        };
+
+        let mut result = lower_defined_function(expr, args, kind);
+        if let Err(ref mut error) = result {
+            if !error.has_expression() {
+                error.set_expression(&expr);
+            }
+        }
+        let (function_name, function) = result?;
+
+        reduced.insert(function_name, function);
+    }
+    Ok(reduced)
+}
+
+/// Parse a single `define-private`/`define-public`/`define-read-only` form's
+/// signature and body into a `ReducedFunction`. Split out from
+/// `lower_defined_functions` so a malformed signature's error can be
+/// attributed back to the defining expression at a single call site, the
+/// same way `ReadOnlyChecker::run` attributes footprint errors back to each
+/// function's `source_expression`.
+fn lower_defined_function(expr: &SymbolicExpression, args: &[SymbolicExpression], kind: DefinedFunctionKind) -> CheckResult<(ClarityName, ReducedFunction)> {
+    check_argument_count(2, args)?;
+
+    let signature = args[0].match_list()
+        .ok_or(CheckErrors::DefineFunctionBadSignature)?;
+    let function_name = signature.get(0)
+        .ok_or(CheckErrors::DefineFunctionBadSignature)?
+        .match_atom().ok_or(CheckErrors::BadFunctionName)?;
+    let arguments = parse_name_type_pairs(&signature[1..])?;
+
+    Ok((function_name.clone(), ReducedFunction {
+        kind,
+        arguments,
+        body: args[1].clone(),
+        source_expression: expr.clone(),
+    }))
+}
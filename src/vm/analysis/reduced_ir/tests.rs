@@ -0,0 +1,24 @@
+use super::*;
+use std::convert::TryFrom;
+
+fn atom(name: &str) -> SymbolicExpression {
+    SymbolicExpression::atom(ClarityName::try_from(name.to_string()).unwrap())
+}
+
+fn list(items: Vec<SymbolicExpression>) -> SymbolicExpression {
+    SymbolicExpression::list(items)
+}
+
+/// A malformed `define-private` signature must still carry the defining
+/// expression on its error, the same way the per-iteration loop it replaced
+/// did -- otherwise the contract's diagnostic points nowhere.
+#[test]
+fn malformed_signature_error_carries_source_expression() {
+    let bad_signature = list(vec![SymbolicExpression::literal_value(vm::types::Value::UInt(1))]);
+    let expr = list(vec![atom("define-private"), bad_signature, SymbolicExpression::literal_value(vm::types::Value::UInt(0))]);
+
+    let contract_analysis = ContractAnalysis::new(vec![expr]);
+    let err = lower_defined_functions(&contract_analysis).unwrap_err();
+
+    assert!(err.has_expression(), "expected the bad-signature error to carry its source expression");
+}
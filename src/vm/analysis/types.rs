@@ -0,0 +1,64 @@
+use std::collections::HashMap;
+
+use vm::representations::{SymbolicExpression, ClarityName};
+use vm::analysis::errors::CheckResult;
+use vm::analysis::read_only_checker::WriteFootprint;
+use vm::analysis::reduced_ir::ReducedFunction;
+
+use super::AnalysisDatabase;
+
+/// A single stage of the contract analysis pipeline. Each pass is handed the
+/// same `ContractAnalysis` in sequence, so later passes can build on the
+/// results (footprints, reduced IR, ...) earlier passes attached to it.
+pub trait AnalysisPass {
+    fn run_pass(contract_analysis: &mut ContractAnalysis, analysis_db: &mut AnalysisDatabase) -> CheckResult<()>;
+}
+
+/// The in-progress and final results of running the analysis pipeline over a
+/// single contract's source.
+pub struct ContractAnalysis {
+    expressions: Vec<SymbolicExpression>,
+    write_footprints: HashMap<ClarityName, WriteFootprint>,
+    reduced_ir: Option<HashMap<ClarityName, ReducedFunction>>,
+}
+
+impl ContractAnalysis {
+    pub fn new(expressions: Vec<SymbolicExpression>) -> ContractAnalysis {
+        ContractAnalysis {
+            expressions,
+            write_footprints: HashMap::new(),
+            reduced_ir: None,
+        }
+    }
+
+    pub fn expressions_iter(&self) -> std::slice::Iter<SymbolicExpression> {
+        self.expressions.iter()
+    }
+
+    pub fn expressions_mut(&mut self) -> &mut Vec<SymbolicExpression> {
+        &mut self.expressions
+    }
+
+    /// Record every defined function's write footprint, as computed by
+    /// `ReadOnlyChecker::run`, so a node's read-only query interface can
+    /// decide whether a call is off-chain evaluable without re-running the
+    /// checker.
+    pub fn read_only_function_footprints(&mut self, footprints: HashMap<ClarityName, WriteFootprint>) {
+        self.write_footprints = footprints;
+    }
+
+    pub fn get_write_footprint(&self, function_name: &ClarityName) -> Option<&WriteFootprint> {
+        self.write_footprints.get(function_name)
+    }
+
+    /// Record the reduced IR built by `lower_defined_functions`, so passes
+    /// that run after `ReadOnlyChecker` can consume it instead of re-lowering
+    /// every `define-*` form themselves.
+    pub fn set_reduced_ir(&mut self, reduced_ir: HashMap<ClarityName, ReducedFunction>) {
+        self.reduced_ir = Some(reduced_ir);
+    }
+
+    pub fn reduced_ir(&self) -> Option<&HashMap<ClarityName, ReducedFunction>> {
+        self.reduced_ir.as_ref()
+    }
+}